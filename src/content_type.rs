@@ -0,0 +1,71 @@
+// content-type correction: charset normalization plus extension-based guessing
+
+use crate::routing::wildmatch;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ContentTypeConfig {
+    /// MIME types (or glob patterns) that should get `; charset=utf-8`
+    /// appended when the upstream didn't specify a charset.
+    #[serde(default = "default_charset_types")]
+    pub charset_types: Vec<String>,
+    /// When the upstream sends `application/octet-stream` or omits
+    /// Content-Type entirely, guess it from the request path's extension.
+    #[serde(default)]
+    pub mime_guess: bool,
+}
+
+fn default_charset_types() -> Vec<String> {
+    vec!["text/plain".to_string()]
+}
+
+impl Default for ContentTypeConfig {
+    fn default() -> Self {
+        Self {
+            charset_types: default_charset_types(),
+            mime_guess: false,
+        }
+    }
+}
+
+/// Normalizes a response's `Content-Type`: optionally fills in (or replaces a
+/// generic `application/octet-stream`) by guessing from `path`'s extension,
+/// then appends `charset=utf-8` if the resulting MIME type is configured to
+/// want one and doesn't already carry a charset. Returns `None` only when the
+/// upstream sent no Content-Type and none was guessed (guessing disabled or
+/// unknown extension); otherwise returns the value to set, which may be the
+/// upstream's own value unchanged.
+pub fn normalize(
+    content_type: Option<&str>,
+    path: &str,
+    config: &ContentTypeConfig,
+) -> Option<String> {
+    let mut content_type = content_type.map(|s| s.to_string());
+
+    if config.mime_guess {
+        let needs_guess = match &content_type {
+            None => true,
+            Some(ct) => ct.starts_with("application/octet-stream"),
+        };
+        if needs_guess {
+            if let Some(guessed) = mime_guess::from_path(path).first_raw() {
+                content_type = Some(guessed.to_string());
+            }
+        }
+    }
+
+    let content_type = content_type?;
+    let mime = content_type.split(';').next().unwrap_or(&content_type).trim();
+
+    let wants_charset = config
+        .charset_types
+        .iter()
+        .any(|pattern| wildmatch(pattern, mime));
+    let has_charset = content_type.to_ascii_lowercase().contains("charset=");
+
+    if wants_charset && !has_charset {
+        Some(format!("{}; charset=utf-8", content_type))
+    } else {
+        Some(content_type)
+    }
+}
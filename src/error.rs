@@ -0,0 +1,90 @@
+// centralizes the mapping from proxy failure modes to HTTP responses
+
+use hyper::{Body, Request, Response, StatusCode};
+use serde::Serialize;
+use std::fmt;
+
+/// Everything that can make `proxy_handler` refuse or fail a request, each
+/// carrying the HTTP status it maps to.
+#[derive(Debug)]
+pub enum ProxyError {
+    BadGateway,
+    BadRequest(String),
+    Forbidden(String),
+    MethodNotAllowed,
+    NoRoute,
+    Unauthorized,
+    UpstreamTimeout,
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyError::BadGateway => write!(f, "bad gateway"),
+            ProxyError::BadRequest(reason) => write!(f, "bad request: {}", reason),
+            ProxyError::Forbidden(reason) => write!(f, "forbidden: {}", reason),
+            ProxyError::MethodNotAllowed => write!(f, "method not allowed"),
+            ProxyError::NoRoute => write!(f, "no route matched this request"),
+            ProxyError::Unauthorized => write!(f, "authentication required"),
+            ProxyError::UpstreamTimeout => write!(f, "upstream timed out"),
+        }
+    }
+}
+
+impl std::error::Error for ProxyError {}
+
+impl ProxyError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ProxyError::BadGateway => StatusCode::BAD_GATEWAY,
+            ProxyError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ProxyError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ProxyError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ProxyError::NoRoute => StatusCode::NOT_FOUND,
+            ProxyError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ProxyError::UpstreamTimeout => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+
+    /// Builds the HTTP response for this error, as plaintext or as a small
+    /// JSON body depending on what the client asked for via `Accept`.
+    pub fn into_response(self, wants_json: bool) -> Response<Body> {
+        #[derive(Serialize)]
+        struct ErrorBody<'a> {
+            error: &'a str,
+        }
+
+        let status = self.status_code();
+        let message = self.to_string();
+        let body = if wants_json {
+            Body::from(
+                serde_json::to_string(&ErrorBody { error: &message })
+                    .unwrap_or_else(|_| "{\"error\":\"internal error\"}".to_string()),
+            )
+        } else {
+            Body::from(message)
+        };
+        Response::builder()
+            .status(status)
+            .header(
+                hyper::header::CONTENT_TYPE,
+                if wants_json {
+                    "application/json"
+                } else {
+                    "text/plain; charset=utf-8"
+                },
+            )
+            .body(body)
+            .unwrap()
+    }
+}
+
+/// True if the request's `Accept` header mentions `application/json` anywhere;
+/// quality values and the order of media types are not considered.
+pub fn wants_json(req: &Request<Body>) -> bool {
+    req.headers()
+        .get(hyper::header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .map(|accept| accept.contains("application/json"))
+        .unwrap_or(false)
+}
@@ -1,13 +1,32 @@
 // proxy to properly show UTF-8, as simple-http-server (https://github.com/TheWaWaR/simple-http-server) does not. (as of Aug 2025)
 // also blocks access to specified folders based on config file
 
+mod content_type;
+mod error;
+mod filter;
+mod metrics;
+mod routing;
+mod script;
+
 use clap::Parser;
+use content_type::ContentTypeConfig;
+use error::ProxyError;
+use filter::FilterConfig;
+use hyper::server::conn::AddrStream;
 use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, Client, Method, Request, Response, Server, StatusCode};
+use hyper::upgrade::Upgraded;
+use hyper::{Body, Client, HeaderMap, Method, Request, Response, Server, StatusCode};
+use metrics::{meter_response_body, Metrics, MetricsConfig};
+use routing::Route;
+use script::Script;
 use serde::{Deserialize, Serialize};
 use std::convert::Infallible;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::copy_bidirectional;
+use tokio::net::TcpStream;
 
 #[derive(Parser, Debug)]
 #[command(name = "proxy")]
@@ -19,17 +38,62 @@ struct Args {
 
 #[derive(Deserialize, Serialize, Debug)]
 struct Config {
-    target: String,
+    /// Routing table, evaluated top-to-bottom; the first route whose host
+    /// and path patterns both match wins.
+    #[serde(default)]
+    routes: Vec<Route>,
+    /// Upstream used when no route matches. If unset, unmatched requests get a 404.
+    #[serde(default)]
+    default_target: Option<String>,
     port: u16,
     blocked_folders: Vec<String>,
+    /// Allow `CONNECT host:port` requests to be tunneled for TLS traffic.
+    #[serde(default)]
+    connect_enabled: bool,
+    /// Glob patterns (matched against `host:port`) that CONNECT is allowed to reach.
+    /// Empty means any destination is allowed once `connect_enabled` is set.
+    #[serde(default)]
+    allowed_connect_targets: Vec<String>,
+    /// Auth/IP/header policies run before forwarding. Empty means no filtering.
+    #[serde(default)]
+    filter: FilterConfig,
+    /// Optional Rhai script exposing `on_request`/`on_response` hooks to
+    /// rewrite traffic without recompiling the proxy.
+    #[serde(default)]
+    script: Option<PathBuf>,
+    /// Content-Type charset normalization and extension-based guessing.
+    #[serde(default)]
+    content_type: ContentTypeConfig,
+    /// Prometheus metrics endpoint for proxied traffic.
+    #[serde(default)]
+    metrics: MetricsConfig,
+    /// How long to wait for the upstream to respond before giving up.
+    #[serde(default = "default_upstream_timeout_ms")]
+    upstream_timeout_ms: u64,
+}
+
+fn default_upstream_timeout_ms() -> u64 {
+    30_000
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            target: "http://localhost:8000".to_string(),
+            routes: vec![Route {
+                host: None,
+                path: "*".to_string(),
+                target: "http://localhost:8000".to_string(),
+            }],
+            default_target: None,
             port: 8080,
             blocked_folders: vec!["private".to_string()],
+            connect_enabled: false,
+            allowed_connect_targets: Vec::new(),
+            filter: FilterConfig::default(),
+            script: None,
+            content_type: ContentTypeConfig::default(),
+            metrics: MetricsConfig::default(),
+            upstream_timeout_ms: default_upstream_timeout_ms(),
         }
     }
 }
@@ -58,57 +122,245 @@ fn is_path_blocked(path: &str, blocked_folders: &[String]) -> bool {
 async fn proxy_handler(
     req: Request<Body>,
     config: Config,
-) -> Result<Response<Body>, Infallible> {
+    client_ip: IpAddr,
+    script: Option<Arc<Script>>,
+    metrics: Option<Arc<Metrics>>,
+) -> Result<Response<Body>, ProxyError> {
+    // run auth/IP/header policies before anything else gets a chance to forward the request,
+    // so a scrape of our own /metrics - or a CONNECT tunnel - can't be used to dodge them
+    let req = filter::filter_request(req, &config.filter, Some(client_ip))?;
+
+    // CONNECT is a tunnel request (typically TLS) and has no path/blocked-folder semantics
+    if req.method() == Method::CONNECT {
+        return handle_connect(req, config).await;
+    }
+
+    // serve metrics directly rather than forwarding, unless they're on their own listener
+    if config.metrics.enabled
+        && config.metrics.bind.is_none()
+        && req.uri().path() == config.metrics.path
+    {
+        let body = metrics.as_ref().map(|m| m.render()).unwrap_or_default();
+        return Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "text/plain; version=0.0.4")
+            .body(Body::from(body))
+            .unwrap());
+    }
+
     // check if the requested path is blocked
     let path = req.uri().path();
     if is_path_blocked(path, &config.blocked_folders) {
-        return Ok(Response::builder()
-            .status(StatusCode::FORBIDDEN)
-            .body(Body::from("Access to this folder is forbidden"))
-            .unwrap());
+        return Err(ProxyError::Forbidden(
+            "access to this folder is forbidden".to_string(),
+        ));
     }
 
     // we only handle GET and POST
     if req.method() != Method::GET && req.method() != Method::POST {
-        return Ok(Response::builder()
-            .status(StatusCode::METHOD_NOT_ALLOWED)
-            .body(Body::from("method not allowed (we only use get and post.)"))
-            .unwrap());
+        return Err(ProxyError::MethodNotAllowed);
+    }
+
+    // resolve which upstream this request should go to
+    let host = req
+        .headers()
+        .get(hyper::header::HOST)
+        .and_then(|h| h.to_str().ok());
+    let mut target = routing::resolve_target(&config.routes, host, path, config.default_target.as_deref())
+        .ok_or(ProxyError::NoRoute)?
+        .to_string();
+
+    // let the request script rewrite method/path/headers/target, or short-circuit entirely
+    let mut method = req.method().clone();
+    let mut forward_path = path.to_string();
+    let mut extra_headers: Vec<(String, String)> = Vec::new();
+    if let Some(script) = &script {
+        if let Some(overlay) = script.on_request(&req) {
+            if let Some(raw_status) = overlay.get("status").and_then(|v| v.as_int().ok()) {
+                let status = u16::try_from(raw_status)
+                    .ok()
+                    .and_then(|status| StatusCode::from_u16(status).ok())
+                    .ok_or_else(|| {
+                        ProxyError::BadRequest(format!(
+                            "script returned invalid status {}",
+                            raw_status
+                        ))
+                    })?;
+                let body = overlay
+                    .get("body")
+                    .and_then(|v| v.clone().into_string().ok())
+                    .unwrap_or_default();
+                return Ok(Response::builder()
+                    .status(status)
+                    .body(Body::from(body))
+                    .unwrap());
+            }
+            if let Some(new_method) = overlay.get("method").and_then(|v| v.clone().into_string().ok()) {
+                if let Ok(m) = Method::from_bytes(new_method.as_bytes()) {
+                    method = m;
+                }
+            }
+            if let Some(new_path) = overlay.get("path").and_then(|v| v.clone().into_string().ok()) {
+                forward_path = new_path;
+            }
+            if let Some(new_target) = overlay.get("target").and_then(|v| v.clone().into_string().ok()) {
+                target = new_target;
+            }
+            if let Some(headers) = overlay.get("headers").and_then(|v| v.clone().try_cast::<rhai::Map>()) {
+                for (name, value) in headers {
+                    if let Ok(value) = value.into_string() {
+                        extra_headers.push((name.to_string(), value));
+                    }
+                }
+            }
+        }
     }
 
     let client = Client::new();
-    let uri = format!("{}{}", config.target, req.uri().path_and_query().map(|x| x.as_str()).unwrap_or("/"));
+    let query = req.uri().query().map(|q| format!("?{}", q)).unwrap_or_default();
+    let uri = format!("{}{}{}", target, forward_path, query);
 
-    // build the forwarded request
-    let mut forwarded_req = Request::builder()
-        .method(req.method())
-        .uri(uri);
+    // copy headers, then apply any script overrides on top; an empty value strips the header
+    let mut forwarded_headers = req.headers().clone();
+    for (name, value) in &extra_headers {
+        let Ok(header_name) = hyper::header::HeaderName::from_bytes(name.as_bytes()) else {
+            continue;
+        };
+        if value.is_empty() {
+            forwarded_headers.remove(&header_name);
+        } else if let Ok(header_value) = hyper::header::HeaderValue::from_str(value) {
+            forwarded_headers.insert(header_name, header_value);
+        }
+    }
 
-    // copy headers
-    for (key, value) in req.headers() {
-        forwarded_req = forwarded_req.header(key, value);
+    // build the forwarded request
+    let mut forwarded_req = Request::builder().method(method).uri(uri);
+    if let Some(headers_mut) = forwarded_req.headers_mut() {
+        *headers_mut = forwarded_headers;
     }
 
     let forwarded_req = forwarded_req.body(req.into_body()).unwrap();
 
-    match client.request(forwarded_req).await {
-        Ok(mut response) => {
-            // modify Content-Type for text/plain responses
-            if let Some(content_type) = response.headers().get("content-type") {
-                if content_type.to_str().unwrap_or("").starts_with("text/plain") {
-                    response.headers_mut().insert(
-                        "content-type",
-                        "text/plain; charset=utf-8".parse().unwrap(),
-                    );
+    let upstream_started_at = Instant::now();
+    let upstream_timeout = Duration::from_millis(config.upstream_timeout_ms);
+    match tokio::time::timeout(upstream_timeout, client.request(forwarded_req)).await {
+        Ok(Ok(mut response)) => {
+            let upstream_latency = upstream_started_at.elapsed();
+
+            // fill in/correct Content-Type and normalize its charset
+            let current_content_type = response
+                .headers()
+                .get("content-type")
+                .and_then(|v| v.to_str().ok());
+            if let Some(new_content_type) =
+                content_type::normalize(current_content_type, &forward_path, &config.content_type)
+            {
+                response
+                    .headers_mut()
+                    .insert("content-type", new_content_type.parse().unwrap());
+            }
+
+            // let the response script adjust status/headers before it goes back to the client
+            if let Some(script) = &script {
+                if let Some(overlay) = script.on_response(&response) {
+                    if let Some(status) = overlay.get("status").and_then(|v| v.as_int().ok()) {
+                        if let Ok(code) = StatusCode::from_u16(status as u16) {
+                            *response.status_mut() = code;
+                        }
+                    }
+                    if let Some(headers) = overlay.get("headers").and_then(|v| v.clone().try_cast::<rhai::Map>()) {
+                        apply_header_overrides(response.headers_mut(), headers);
+                    }
                 }
             }
+
+            if let Some(metrics) = &metrics {
+                meter_response_body(&mut response, metrics.clone(), target.clone(), upstream_latency);
+            }
+
             Ok(response)
         }
-        Err(_) => Ok(Response::builder()
-            .status(StatusCode::BAD_GATEWAY)
-            .body(Body::from("Bad Gateway"))
-            .unwrap()),
+        Ok(Err(_)) => {
+            if let Some(metrics) = &metrics {
+                metrics.record(
+                    &target,
+                    ProxyError::BadGateway.status_code().as_u16(),
+                    upstream_started_at.elapsed(),
+                    0,
+                );
+            }
+            Err(ProxyError::BadGateway)
+        }
+        Err(_) => {
+            if let Some(metrics) = &metrics {
+                metrics.record(
+                    &target,
+                    ProxyError::UpstreamTimeout.status_code().as_u16(),
+                    upstream_started_at.elapsed(),
+                    0,
+                );
+            }
+            Err(ProxyError::UpstreamTimeout)
+        }
+    }
+}
+
+fn apply_header_overrides(headers: &mut HeaderMap, overrides: rhai::Map) {
+    for (name, value) in overrides {
+        let Ok(value) = value.into_string() else {
+            continue;
+        };
+        if let (Ok(name), Ok(value)) = (
+            hyper::header::HeaderName::from_bytes(name.as_bytes()),
+            value.parse(),
+        ) {
+            headers.insert(name, value);
+        }
+    }
+}
+
+/// Handles `CONNECT host:port` by upgrading the connection and splicing bytes
+/// between the client and the requested upstream, for TLS tunneling.
+async fn handle_connect(req: Request<Body>, config: Config) -> Result<Response<Body>, ProxyError> {
+    if !config.connect_enabled {
+        return Err(ProxyError::MethodNotAllowed);
+    }
+
+    let authority = req
+        .uri()
+        .authority()
+        .map(|a| a.to_string())
+        .ok_or_else(|| ProxyError::BadRequest("CONNECT request must target host:port".to_string()))?;
+
+    let allowed = config.allowed_connect_targets.is_empty()
+        || config
+            .allowed_connect_targets
+            .iter()
+            .any(|pattern| routing::wildmatch(pattern, &authority));
+    if !allowed {
+        return Err(ProxyError::Forbidden(
+            "CONNECT to this destination is forbidden".to_string(),
+        ));
     }
+
+    tokio::task::spawn(async move {
+        match hyper::upgrade::on(req).await {
+            Ok(upgraded) => {
+                if let Err(e) = tunnel(upgraded, &authority).await {
+                    eprintln!("tunnel error for {}: {}", authority, e);
+                }
+            }
+            Err(e) => eprintln!("upgrade error: {}", e),
+        }
+    });
+
+    Ok(Response::new(Body::empty()))
+}
+
+async fn tunnel(mut upgraded: Upgraded, authority: &str) -> std::io::Result<()> {
+    let mut upstream = TcpStream::connect(authority).await?;
+    copy_bidirectional(&mut upgraded, &mut upstream).await?;
+    Ok(())
 }
 
 #[tokio::main]
@@ -135,20 +387,57 @@ async fn main() {
     
     let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
 
+    let script = match &config.script {
+        Some(path) => match Script::load(path) {
+            Ok(script) => Some(Arc::new(script)),
+            Err(e) => {
+                eprintln!("failed to load script '{}': {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let metrics = if config.metrics.enabled {
+        Some(Arc::new(Metrics::new()))
+    } else {
+        None
+    };
+    if let (Some(metrics), Some(bind)) = (&metrics, config.metrics.bind) {
+        tokio::spawn(metrics::serve(bind, metrics.clone()));
+    }
+
     let config_clone = config.clone();
-    let make_svc = make_service_fn(move |_conn| {
+    let make_svc = make_service_fn(move |conn: &AddrStream| {
         let config = config_clone.clone();
+        let script = script.clone();
+        let metrics = metrics.clone();
+        let client_ip = conn.remote_addr().ip();
         async move {
             Ok::<_, Infallible>(service_fn(move |req| {
                 let config = config.clone();
-                proxy_handler(req, config)
+                let script = script.clone();
+                let metrics = metrics.clone();
+                async move {
+                    let wants_json = error::wants_json(&req);
+                    Ok::<_, Infallible>(
+                        match proxy_handler(req, config, client_ip, script, metrics).await {
+                            Ok(response) => response,
+                            Err(err) => err.into_response(wants_json),
+                        },
+                    )
+                }
             }))
         }
     });
 
     let server = Server::bind(&addr).serve(make_svc);
 
-    println!("proxy running at http://localhost:{}, forwarding to {}", config.port, config.target);
+    println!("proxy running at http://localhost:{}", config.port);
+    println!("routes: {:?}", config.routes);
+    if let Some(default_target) = &config.default_target {
+        println!("default target: {}", default_target);
+    }
     println!("blocked folders: {:?}", config.blocked_folders);
     println!("config loaded from: {}", args.config.display());
 
@@ -161,9 +450,17 @@ async fn main() {
 impl Clone for Config {
     fn clone(&self) -> Self {
         Self {
-            target: self.target.clone(),
+            routes: self.routes.clone(),
+            default_target: self.default_target.clone(),
             port: self.port,
             blocked_folders: self.blocked_folders.clone(),
+            connect_enabled: self.connect_enabled,
+            allowed_connect_targets: self.allowed_connect_targets.clone(),
+            filter: self.filter.clone(),
+            script: self.script.clone(),
+            content_type: self.content_type.clone(),
+            metrics: self.metrics.clone(),
+            upstream_timeout_ms: self.upstream_timeout_ms,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,249 @@
+// Prometheus metrics for proxied traffic: per-route counts, status buckets,
+// upstream latency histograms, and bytes transferred
+
+use futures_util::Stream;
+use hyper::body::Bytes;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Response, Server};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MetricsConfig {
+    /// Enable the metrics subsystem and endpoint.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Path the metrics are served on. Ignored if `bind` is set.
+    #[serde(default = "default_metrics_path")]
+    pub path: String,
+    /// Optional separate address to serve metrics on, so `/metrics` isn't
+    /// reachable through the main listener's proxied traffic.
+    #[serde(default)]
+    pub bind: Option<SocketAddr>,
+}
+
+fn default_metrics_path() -> String {
+    "/metrics".to_string()
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: default_metrics_path(),
+            bind: None,
+        }
+    }
+}
+
+const LATENCY_BUCKETS_MS: &[f64] = &[
+    5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0,
+];
+
+struct RouteStats {
+    requests_total: u64,
+    status_counts: HashMap<u16, u64>,
+    bytes_transferred: u64,
+    latency_buckets: Vec<u64>, // parallel to LATENCY_BUCKETS_MS, plus a trailing +Inf bucket
+    latency_sum_ms: f64,
+}
+
+impl Default for RouteStats {
+    fn default() -> Self {
+        Self {
+            requests_total: 0,
+            status_counts: HashMap::new(),
+            bytes_transferred: 0,
+            latency_buckets: vec![0; LATENCY_BUCKETS_MS.len() + 1],
+            latency_sum_ms: 0.0,
+        }
+    }
+}
+
+/// Process-wide metrics registry, keyed by route target behind a single lock.
+#[derive(Default)]
+pub struct Metrics {
+    routes: Mutex<HashMap<String, RouteStats>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the outcome of one proxied request.
+    pub fn record(&self, route: &str, status: u16, latency: Duration, bytes: u64) {
+        let mut routes = self.routes.lock().unwrap();
+        let stats = routes.entry(route.to_string()).or_default();
+
+        stats.requests_total += 1;
+        *stats.status_counts.entry(status).or_insert(0) += 1;
+        stats.bytes_transferred += bytes;
+
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        stats.latency_sum_ms += latency_ms;
+        let bucket_index = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&bound| latency_ms <= bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        for bucket in &mut stats.latency_buckets[bucket_index..] {
+            *bucket += 1;
+        }
+    }
+
+    /// Renders all recorded metrics in Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let routes = self.routes.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str("# HELP proxy_requests_total Total proxied requests per route.\n");
+        out.push_str("# TYPE proxy_requests_total counter\n");
+        for (route, stats) in routes.iter() {
+            out.push_str(&format!(
+                "proxy_requests_total{{route=\"{}\"}} {}\n",
+                route, stats.requests_total
+            ));
+        }
+
+        out.push_str("# HELP proxy_responses_total Proxied responses per route and status code.\n");
+        out.push_str("# TYPE proxy_responses_total counter\n");
+        for (route, stats) in routes.iter() {
+            for (status, count) in &stats.status_counts {
+                out.push_str(&format!(
+                    "proxy_responses_total{{route=\"{}\",status=\"{}\"}} {}\n",
+                    route, status, count
+                ));
+            }
+        }
+
+        out.push_str("# HELP proxy_bytes_transferred_total Response bytes transferred per route.\n");
+        out.push_str("# TYPE proxy_bytes_transferred_total counter\n");
+        for (route, stats) in routes.iter() {
+            out.push_str(&format!(
+                "proxy_bytes_transferred_total{{route=\"{}\"}} {}\n",
+                route, stats.bytes_transferred
+            ));
+        }
+
+        out.push_str("# HELP proxy_upstream_latency_ms Upstream request latency in milliseconds.\n");
+        out.push_str("# TYPE proxy_upstream_latency_ms histogram\n");
+        for (route, stats) in routes.iter() {
+            for (i, bound) in LATENCY_BUCKETS_MS.iter().enumerate() {
+                out.push_str(&format!(
+                    "proxy_upstream_latency_ms_bucket{{route=\"{}\",le=\"{}\"}} {}\n",
+                    route, bound, stats.latency_buckets[i]
+                ));
+            }
+            out.push_str(&format!(
+                "proxy_upstream_latency_ms_bucket{{route=\"{}\",le=\"+Inf\"}} {}\n",
+                route,
+                stats.latency_buckets[LATENCY_BUCKETS_MS.len()]
+            ));
+            out.push_str(&format!(
+                "proxy_upstream_latency_ms_sum{{route=\"{}\"}} {}\n",
+                route, stats.latency_sum_ms
+            ));
+            out.push_str(&format!(
+                "proxy_upstream_latency_ms_count{{route=\"{}\"}} {}\n",
+                route, stats.requests_total
+            ));
+        }
+
+        out
+    }
+}
+
+/// Wraps a response body stream, counting bytes as they pass through and
+/// recording the request's outcome once the stream is dropped (whether it
+/// ran to completion or failed partway through). This lets us account bytes
+/// transferred without buffering the whole body, so streamed/chunked and SSE
+/// responses keep flowing to the client unchanged.
+struct MeteredBody<S> {
+    inner: S,
+    metrics: Arc<Metrics>,
+    route: String,
+    status: u16,
+    upstream_latency: Duration,
+    bytes_seen: u64,
+}
+
+impl<S> Stream for MeteredBody<S>
+where
+    S: Stream<Item = Result<Bytes, hyper::Error>> + Unpin,
+{
+    type Item = Result<Bytes, hyper::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = Pin::new(&mut self.inner).poll_next(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &poll {
+            self.bytes_seen += chunk.len() as u64;
+        }
+        poll
+    }
+}
+
+impl<S> Drop for MeteredBody<S> {
+    fn drop(&mut self) {
+        self.metrics.record(
+            &self.route,
+            self.status,
+            self.upstream_latency,
+            self.bytes_seen,
+        );
+    }
+}
+
+/// Replaces `response`'s body with one that streams through unchanged,
+/// counting bytes as the client reads it and recording
+/// `route`/`status`/`upstream_latency`/bytes-transferred to `metrics` once the
+/// body is dropped, instead of buffering the whole response up front.
+pub fn meter_response_body(
+    response: &mut Response<Body>,
+    metrics: Arc<Metrics>,
+    route: String,
+    upstream_latency: Duration,
+) {
+    let status = response.status().as_u16();
+    let body = std::mem::replace(response.body_mut(), Body::empty());
+    let metered = MeteredBody {
+        inner: body,
+        metrics,
+        route,
+        status,
+        upstream_latency,
+        bytes_seen: 0,
+    };
+    *response.body_mut() = Body::wrap_stream(metered);
+}
+
+/// Serves the rendered metrics on `addr`, independent of the main proxy
+/// listener, so scraping doesn't go through routing/filtering/forwarding.
+pub async fn serve(addr: SocketAddr, metrics: Arc<Metrics>) {
+    let make_svc = make_service_fn(move |_conn| {
+        let metrics = metrics.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |_req| {
+                let metrics = metrics.clone();
+                async move {
+                    Ok::<_, Infallible>(
+                        Response::builder()
+                            .status(200)
+                            .header("content-type", "text/plain; version=0.0.4")
+                            .body(Body::from(metrics.render()))
+                            .unwrap(),
+                    )
+                }
+            }))
+        }
+    });
+
+    if let Err(e) = Server::bind(&addr).serve(make_svc).await {
+        eprintln!("metrics server error: {}", e);
+    }
+}
@@ -0,0 +1,172 @@
+// host/path based routing table with wildcard (`*`/`?`) matching
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Route {
+    /// Glob pattern matched against the `Host` header with any `:port` suffix
+    /// stripped first. `None` matches any host.
+    #[serde(default)]
+    pub host: Option<String>,
+    /// Glob pattern matched against the request path. Defaults to `*` (match everything).
+    #[serde(default = "default_path_pattern")]
+    pub path: String,
+    /// Upstream to forward matching requests to, e.g. `http://localhost:8000`.
+    pub target: String,
+}
+
+fn default_path_pattern() -> String {
+    "*".to_string()
+}
+
+/// Matches `text` against a shell-style glob `pattern` where `*` matches any
+/// run of characters (including none) and `?` matches exactly one character.
+///
+/// Uses the standard two-pointer scan (tracking the most recent `*` and
+/// retrying from there on a mismatch) rather than naive backtracking
+/// recursion, so it stays bounded by pattern length times text length
+/// (quadratic at worst) instead of blowing up exponentially on
+/// adversarial patterns like `*a*a*a*b` against a long non-matching text -
+/// both pattern and text here can be attacker-controlled (routes, paths,
+/// header values).
+pub fn wildmatch(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None; // (position in pattern after '*', resume point in text)
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p + 1, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p;
+            t = star_t + 1;
+            star = Some((star_p, t));
+        } else {
+            return false;
+        }
+    }
+    while p < pattern.len() && pattern[p] == '*' {
+        p += 1;
+    }
+    p == pattern.len()
+}
+
+/// Strips a trailing `:port` from a `Host` header value, leaving a bracketed
+/// IPv6 literal (`[::1]`) intact, so `host` patterns don't have to account
+/// for whatever port the client happened to send.
+fn host_without_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        return match host.find(']') {
+            Some(close) => &host[..=close],
+            None => host,
+        };
+    }
+    match host.rsplit_once(':') {
+        Some((h, port)) if !h.is_empty() && port.chars().all(|c| c.is_ascii_digit()) => h,
+        _ => host,
+    }
+}
+
+/// Walks `routes` top-to-bottom and returns the first whose host and path
+/// patterns both match, falling back to `default_target` if none match.
+pub fn resolve_target<'a>(
+    routes: &'a [Route],
+    host: Option<&str>,
+    path: &str,
+    default_target: Option<&'a str>,
+) -> Option<&'a str> {
+    for route in routes {
+        let host_matches = match &route.host {
+            Some(pattern) => host
+                .map(|h| wildmatch(pattern, host_without_port(h)))
+                .unwrap_or(false),
+            None => true,
+        };
+        if host_matches && wildmatch(&route.path, path) {
+            return Some(&route.target);
+        }
+    }
+    default_target
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildmatch_leading_star() {
+        assert!(wildmatch("*.example.com", "api.example.com"));
+        assert!(!wildmatch("*.example.com", "example.com"));
+    }
+
+    #[test]
+    fn wildmatch_trailing_star() {
+        assert!(wildmatch("/static/*", "/static/js/app.js"));
+        assert!(wildmatch("/static/*", "/static/"));
+        assert!(!wildmatch("/static/*", "/other"));
+    }
+
+    #[test]
+    fn wildmatch_multiple_stars() {
+        assert!(wildmatch("*a*a*a*b", "aaaaaaaaaaaaaaaaaaaaaaaaaaab"));
+        assert!(!wildmatch("*a*a*a*b", &"a".repeat(200)));
+    }
+
+    #[test]
+    fn wildmatch_question_mark_at_end() {
+        assert!(wildmatch("/item/?", "/item/1"));
+        assert!(!wildmatch("/item/?", "/item/"));
+        assert!(!wildmatch("/item/?", "/item/12"));
+    }
+
+    #[test]
+    fn wildmatch_empty_pattern_and_text() {
+        assert!(wildmatch("", ""));
+        assert!(!wildmatch("", "x"));
+        assert!(!wildmatch("x", ""));
+        assert!(wildmatch("*", ""));
+    }
+
+    fn route(host: Option<&str>, path: &str, target: &str) -> Route {
+        Route {
+            host: host.map(str::to_string),
+            path: path.to_string(),
+            target: target.to_string(),
+        }
+    }
+
+    #[test]
+    fn resolve_target_strips_port_from_host() {
+        let routes = vec![route(Some("example.com"), "*", "http://upstream")];
+        assert_eq!(
+            resolve_target(&routes, Some("example.com:8080"), "/", None),
+            Some("http://upstream")
+        );
+    }
+
+    #[test]
+    fn resolve_target_first_match_wins() {
+        let routes = vec![
+            route(Some("*"), "/a", "http://first"),
+            route(Some("*"), "*", "http://second"),
+        ];
+        assert_eq!(resolve_target(&routes, Some("x"), "/a", None), Some("http://first"));
+        assert_eq!(resolve_target(&routes, Some("x"), "/b", None), Some("http://second"));
+    }
+
+    #[test]
+    fn resolve_target_falls_back_to_default() {
+        let routes = vec![route(Some("other.example.com"), "*", "http://upstream")];
+        assert_eq!(
+            resolve_target(&routes, Some("example.com"), "/", Some("http://default")),
+            Some("http://default")
+        );
+        assert_eq!(resolve_target(&[], None, "/", None), None);
+    }
+}
@@ -0,0 +1,74 @@
+// optional Rhai hooks for rewriting requests/responses without recompiling
+
+use hyper::{Body, Request, Response};
+use rhai::{Engine, Map, Scope, AST};
+use std::path::Path;
+
+/// A compiled Rhai script exposing optional `on_request(req)` /
+/// `on_response(resp)` hooks. Each hook receives a plain object map
+/// (`method`, `path`, `status`, `headers`, ...) and returns a map of the
+/// fields it wants to change; fields it leaves out are passed through as-is.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let engine = Engine::new();
+        let ast = engine.compile_file(path.to_path_buf())?;
+        Ok(Self { engine, ast })
+    }
+
+    fn has_fn(&self, name: &str) -> bool {
+        self.ast.iter_functions().any(|f| f.name == name)
+    }
+
+    /// Runs `on_request`, if defined, with a map describing the proxied
+    /// request. Returns the overlay map of fields the script wants changed.
+    pub fn on_request(&self, req: &Request<Body>) -> Option<Map> {
+        if !self.has_fn("on_request") {
+            return None;
+        }
+
+        let mut map = Map::new();
+        map.insert("method".into(), req.method().as_str().into());
+        map.insert("path".into(), req.uri().path().into());
+        map.insert("headers".into(), headers_to_map(req.headers()).into());
+
+        self.call("on_request", map)
+    }
+
+    /// Runs `on_response`, if defined, with a map describing the upstream
+    /// response. Returns the overlay map of fields the script wants changed.
+    pub fn on_response(&self, resp: &Response<Body>) -> Option<Map> {
+        if !self.has_fn("on_response") {
+            return None;
+        }
+
+        let mut map = Map::new();
+        map.insert("status".into(), (resp.status().as_u16() as i64).into());
+        map.insert("headers".into(), headers_to_map(resp.headers()).into());
+
+        self.call("on_response", map)
+    }
+
+    fn call(&self, fn_name: &str, arg: Map) -> Option<Map> {
+        let mut scope = Scope::new();
+        match self.engine.call_fn::<Map>(&mut scope, &self.ast, fn_name, (arg,)) {
+            Ok(result) => Some(result),
+            Err(e) => {
+                eprintln!("{} script hook failed: {}", fn_name, e);
+                None
+            }
+        }
+    }
+}
+
+fn headers_to_map(headers: &hyper::HeaderMap) -> Map {
+    let mut map = Map::new();
+    for (key, value) in headers {
+        map.insert(key.as_str().into(), value.to_str().unwrap_or("").into());
+    }
+    map
+}